@@ -3,38 +3,65 @@ use std::ops::{Index, IndexMut};
 use std::io::prelude::*;
 use std::io;
 use std::fs::File;
+use std::env;
+use std::time::{Duration, Instant};
 
 const PRINT: bool = true;
 
 type Cell = usize;
 type Value = usize;
 type CellSet = Vec<Cell>;
-type ValueSet = Vec<Value>;
 type UnitList = Vec<Vec<CellSet>>;
 type PeerList = Vec<CellSet>;
+
+// Box geometry. The side length is `n = box_rows * box_cols` and the grid has
+// `n * n` cells, so 3x3 boxes give the classic 9x9 puzzle, 2x3 boxes give 6x6,
+// 4x4 boxes give 16x16, and so on.
+#[derive(Debug, Clone, Copy)]
+struct Config {
+    box_rows: usize,
+    box_cols: usize,
+}
+impl Config {
+    fn n(&self) -> usize {
+        self.box_rows * self.box_cols
+    }
+    fn cells(&self) -> usize {
+        let n = self.n();
+        n * n
+    }
+}
+
 #[derive(Debug, Clone)]
 struct Problem {
     units: UnitList,
     peers: PeerList,
+    cfg: Config,
 }
-#[derive(Debug, Clone)]
+// Each cell's candidates are a bitmask: bit `v-1` set means value `v` is still
+// possible. This keeps propagation allocation-free per cell; `count_ones` gives
+// the candidate count and `trailing_zeros` reads the sole remaining value.
+type Mask = u32;
+#[derive(Debug, Clone, PartialEq)]
 struct Board {
-    constraints: Vec<ValueSet>,
+    constraints: Vec<Mask>,
 }
-fn delete(set: &mut Vec<Value>, v: Value) {
-    if let Some(i) = set.iter().position(|&e| e == v) {
-        set.remove(i);
-    }
+fn delete(set: &mut Mask, v: Value) {
+    *set &= !(1 << (v - 1));
+}
+
+fn has(set: Mask, v: Value) -> bool {
+    set & (1 << (v - 1)) != 0
 }
 
 impl Index<usize> for Board {
-    type Output = Vec<Value>;
-    fn index(&self, i: usize) -> &Vec<Value> {
+    type Output = Mask;
+    fn index(&self, i: usize) -> &Mask {
         &self.constraints[i]
     }
 }
 impl IndexMut<usize> for Board {
-    fn index_mut(&mut self, i: usize) -> &mut Vec<Value> {
+    fn index_mut(&mut self, i: usize) -> &mut Mask {
         &mut self.constraints[i]
     }
 }
@@ -46,21 +73,20 @@ enum Outcome {
 }
 
 impl Board {
-    fn new() -> Board {
-        fn any() -> CellSet {
-            (1..10).collect()
-        }
-        Board { constraints: vec![any(); 81] }
+    fn new(cfg: Config) -> Board {
+        // all values possible: bits 0..n set
+        let any = (1 << cfg.n()) - 1;
+        Board { constraints: vec![any; cfg.cells()] }
     }
 
     // If a cell has 0 options -> Failed
     // If all cells have 1 option -> Done
     // Otherwise, -> index of undetermined cell with least options
     fn solved(&self) -> Outcome {
-        let mut cell = 81;
-        let mut best = 10;
+        let mut cell = self.constraints.len();
+        let mut best = u32::max_value();
         for (i, constraint) in self.constraints.iter().enumerate() {
-            let l = constraint.len();
+            let l = constraint.count_ones();
             if l == 0 {
                 return Outcome::Failed;
             }
@@ -69,46 +95,49 @@ impl Board {
                 best = l;
             }
         }
-        if cell == 81 {
+        if cell == self.constraints.len() {
             return Outcome::Done;
         }
         return Outcome::Next(cell as usize);
     }
 
     fn assign(&mut self, pr: &Problem, c: Cell, v: Value) -> bool {
-        let mut cs = self[c].clone();
+        let mut cs = self[c];
         delete(&mut cs, v);
-        for v in &cs {
-            if !self.eliminate(pr, c, *v) {
-                return false;
+        for v in 1..(pr.cfg.n() + 1) {
+            if has(cs, v) {
+                if !self.eliminate(pr, c, v) {
+                    return false;
+                }
             }
         }
         true
     }
 
     fn eliminate(&mut self, pr: &Problem, c: Cell, v: Value) -> bool {
-        if !(self[c].contains(&v)) {
+        if !has(self[c], v) {
             return true;
         }
         delete(&mut self[c], v);
-        let others = self[c].clone();
-        if others.len() == 0 {
-            return false;
-        }
-        if others.len() == 1 {
-            let val = others[0];
-            for peer in &pr.peers[c] {
-                if *peer != c {
-                    if !self.eliminate(pr, *peer, val) {
-                        return false;
+        let others = self[c];
+        match others.count_ones() {
+            0 => return false,
+            1 => {
+                let val = (others.trailing_zeros() + 1) as Value;
+                for peer in &pr.peers[c] {
+                    if *peer != c {
+                        if !self.eliminate(pr, *peer, val) {
+                            return false;
+                        }
                     }
                 }
             }
+            _ => {}
         }
         for u in &pr.units[c] {
             let mut places = Vec::new();
             for cell in u {
-                if self[*cell].contains(&v) {
+                if has(self[*cell], v) {
                     places.push(*cell);
                 }
             }
@@ -126,19 +155,24 @@ impl Board {
         true
     }
 
-    // Tries to solve the current board
-    fn search(&mut self, pr: &Problem) -> Option<Board> {
+    // Tries to solve the current board. `nodes` accumulates the number of
+    // assignment branches explored, for benchmarking.
+    fn search(&mut self, pr: &Problem, nodes: &mut usize) -> Option<Board> {
         match self.solved() {
             Outcome::Failed => return None,
             Outcome::Done => return Some(self.clone()),
             // Returns the cell with smallest number of possibilities
             Outcome::Next(c) => {
-                for v in &self[c] {
-                    let mut new = self.clone();
-                    //println!("trying {}:{}", c, *v);
-                    if new.assign(pr, c, *v) {
-                        if let Some(b) = new.search(pr) {
-                            return Some(b);
+                let options = self[c];
+                for v in 1..(pr.cfg.n() + 1) {
+                    if has(options, v) {
+                        *nodes += 1;
+                        let mut new = self.clone();
+                        //println!("trying {}:{}", c, v);
+                        if new.assign(pr, c, v) {
+                            if let Some(b) = new.search(pr, nodes) {
+                                return Some(b);
+                            }
                         }
                     }
                 }
@@ -146,41 +180,87 @@ impl Board {
             }
         }
     }
+
+    // Enumerates solutions by backtracking like `search`, but instead of
+    // returning on the first `Done` it tallies each one and keeps exploring.
+    // Stops as soon as `limit` solutions are found, so callers can cheaply
+    // distinguish "unique" from "multiple".
+    fn count_solutions(&mut self, pr: &Problem, limit: usize) -> usize {
+        match self.solved() {
+            Outcome::Failed => 0,
+            Outcome::Done => 1,
+            Outcome::Next(c) => {
+                let mut count = 0;
+                let options = self[c];
+                for v in 1..(pr.cfg.n() + 1) {
+                    if has(options, v) {
+                        let mut new = self.clone();
+                        if new.assign(pr, c, v) {
+                            count += new.count_solutions(pr, limit - count);
+                            if count >= limit {
+                                return count;
+                            }
+                        }
+                    }
+                }
+                count
+            }
+        }
+    }
+
+    // A well-formed puzzle has exactly one solution.
+    fn has_unique_solution(&mut self, pr: &Problem) -> bool {
+        self.count_solutions(pr, 2) == 1
+    }
+}
+
+// Maps a grid character to its value, accepting digits and letters so that
+// boards larger than 9x9 can use e.g. `a`..`g` for values 10..16.
+fn char_value(c: char) -> Option<Value> {
+    c.to_digit(36).map(|d| d as Value)
+}
+
+// Inverse of `char_value`, used when rendering a solved cell.
+fn value_char(v: Value) -> char {
+    std::char::from_digit(v as u32, 36).unwrap_or('?')
 }
 
 // Initializes sets representing the basic constraints of a sudoku puzzle
-fn make_units() -> Problem {
+fn make_units(cfg: Config) -> Problem {
+    let n = cfg.n();
     let mut unit_set: Vec<CellSet> = Vec::new();
     // columns
-    for col in 0..9 {
+    for col in 0..n {
         let mut a = Vec::new();
-        for row in 0..9 {
-            a.push(row * 9 + col);
+        for row in 0..n {
+            a.push(row * n + col);
         }
         unit_set.push(a)
     }
     // rows
-    for row in 0..9 {
+    for row in 0..n {
         let mut a = Vec::new();
-        for col in 0..9 {
-            a.push(row * 9 + col);
+        for col in 0..n {
+            a.push(row * n + col);
         }
         unit_set.push(a)
     }
-    // boxes
-    for rs in &[[0, 1, 2], [3, 4, 5], [6, 7, 8]] {
-        for cs in &[[0, 1, 2], [3, 4, 5], [6, 7, 8]] {
+    // boxes: each box is box_rows tall and box_cols wide
+    for band in 0..(n / cfg.box_rows) {
+        for stack in 0..(n / cfg.box_cols) {
             let mut a = Vec::new();
-            for r in rs {
-                for c in cs {
-                    a.push((r * 9 + c) as usize);
+            for r in 0..cfg.box_rows {
+                for c in 0..cfg.box_cols {
+                    let row = band * cfg.box_rows + r;
+                    let col = stack * cfg.box_cols + c;
+                    a.push(row * n + col);
                 }
             }
             unit_set.push(a);
         }
     }
 
-    let mut units: UnitList = vec![Vec::new(); 81];
+    let mut units: UnitList = vec![Vec::new(); cfg.cells()];
     let mut peers: PeerList = Vec::new();
 
     for u in &unit_set {
@@ -202,6 +282,7 @@ fn make_units() -> Problem {
     Problem {
         peers: peers,
         units: units,
+        cfg: cfg,
     }
 }
 
@@ -214,20 +295,285 @@ fn load(file: &str) -> Result<Vec<String>, io::Error> {
 
 
 fn render_board(b: &Board) -> Vec<String> {
-    let mut res = vec![String::new(); 81];
+    let mut res = vec![String::new(); b.constraints.len()];
     for (i, constraint) in b.constraints.iter().enumerate() {
-        if constraint.len() == 1 {
-            res[i] = format!("{}", constraint[0]);
+        let n = constraint.count_ones();
+        if n == 1 {
+            res[i] = format!("{}", value_char((constraint.trailing_zeros() + 1) as Value));
         } else {
-            res[i] = format!("*{}", constraint.len());
+            res[i] = format!("*{}", n);
         }
     }
     res
 }
 
-fn solve_puzzle(s: &str) -> Option<Board> {
-    let pr = make_units();
-    let mut b = Board::new();
+// ---- Puzzle generation -------------------------------------------------
+// A tiny xorshift PRNG, seeded from the clock, so the generator needs no
+// external dependency.
+struct Rng {
+    state: u64,
+}
+impl Rng {
+    fn new() -> Rng {
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0x2545F4914F6CDD1D);
+        Rng { state: seed | 1 }
+    }
+    fn next(&mut self) -> u64 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.state = x;
+        x
+    }
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+    fn shuffle<T>(&mut self, v: &mut Vec<T>) {
+        for i in (1..v.len()).rev() {
+            let j = self.below(i + 1);
+            v.swap(i, j);
+        }
+    }
+}
+
+// Backtracking fill that tries candidate values in random order, yielding a
+// random complete grid.
+fn fill(b: &Board, pr: &Problem, rng: &mut Rng) -> Option<Board> {
+    match b.solved() {
+        Outcome::Failed => None,
+        Outcome::Done => Some(b.clone()),
+        Outcome::Next(c) => {
+            let mut vals: Vec<Value> = (1..(pr.cfg.n() + 1)).filter(|&v| has(b[c], v)).collect();
+            rng.shuffle(&mut vals);
+            for v in vals {
+                let mut new = b.clone();
+                if new.assign(pr, c, v) {
+                    if let Some(done) = fill(&new, pr, rng) {
+                        return Some(done);
+                    }
+                }
+            }
+            None
+        }
+    }
+}
+
+// Count the solutions of the puzzle described by `givens` (up to two).
+fn count_from_givens(givens: &[Option<Value>], pr: &Problem) -> usize {
+    let mut b = Board::new(pr.cfg);
+    for (c, g) in givens.iter().enumerate() {
+        if let Some(v) = *g {
+            if !b.assign(pr, c, v) {
+                return 0;
+            }
+        }
+    }
+    b.count_solutions(pr, 2)
+}
+
+// Produces a random puzzle with a unique solution: fill a complete grid, then
+// remove clues in random order, backing out any removal that makes the puzzle
+// ambiguous, until the target clue count or no further removable cell remains.
+fn generate(pr: &Problem, clues: usize) -> String {
+    let cfg = pr.cfg;
+    let mut rng = Rng::new();
+    let full = loop {
+        if let Some(b) = fill(&Board::new(cfg), pr, &mut rng) {
+            break b;
+        }
+    };
+    let mut givens: Vec<Option<Value>> = (0..cfg.cells())
+        .map(|i| Some((full[i].trailing_zeros() + 1) as Value))
+        .collect();
+    let mut order: Vec<Cell> = (0..cfg.cells()).collect();
+    rng.shuffle(&mut order);
+    let mut remaining = cfg.cells();
+    for c in order {
+        if remaining <= clues {
+            break;
+        }
+        let saved = givens[c];
+        givens[c] = None;
+        if count_from_givens(&givens, pr) == 1 {
+            remaining -= 1;
+        } else {
+            givens[c] = saved; // removal created ambiguity, back out
+        }
+    }
+    givens
+        .iter()
+        .map(|g| match *g {
+            Some(v) => value_char(v),
+            None => '.',
+        })
+        .collect()
+}
+
+// ---- CNF / DPLL backend ------------------------------------------------
+// A second engine: encode the puzzle into CNF and solve it with a small
+// built-in DPLL procedure, so the crate doubles as a teaching comparison
+// between Norvig-style propagation and SAT.
+
+// One boolean variable per (cell, value) triple.
+type Lit = i32;
+type Clause = Vec<Lit>;
+
+fn var(cell: Cell, v: Value, n: usize) -> usize {
+    cell * n + (v - 1)
+}
+
+fn pos(cell: Cell, v: Value, n: usize) -> Lit {
+    (var(cell, v, n) as Lit) + 1
+}
+
+fn neg(cell: Cell, v: Value, n: usize) -> Lit {
+    -pos(cell, v, n)
+}
+
+// Collect the distinct units (rows, columns, boxes) from the problem.
+fn unit_list(pr: &Problem) -> Vec<CellSet> {
+    let mut seen: Vec<CellSet> = Vec::new();
+    for cell in 0..pr.cfg.cells() {
+        for u in &pr.units[cell] {
+            let mut s = u.clone();
+            s.sort();
+            if !seen.contains(&s) {
+                seen.push(s);
+            }
+        }
+    }
+    seen
+}
+
+fn encode_cnf(pr: &Problem, givens: &[Option<Value>]) -> Vec<Clause> {
+    let n = pr.cfg.n();
+    let mut clauses: Vec<Clause> = Vec::new();
+    // Each cell takes at least one value, and at most one.
+    for cell in 0..pr.cfg.cells() {
+        clauses.push((1..(n + 1)).map(|v| pos(cell, v, n)).collect());
+        for v1 in 1..(n + 1) {
+            for v2 in (v1 + 1)..(n + 1) {
+                clauses.push(vec![neg(cell, v1, n), neg(cell, v2, n)]);
+            }
+        }
+    }
+    // Each value appears at least once and at most once within every unit.
+    for u in &unit_list(pr) {
+        for v in 1..(n + 1) {
+            clauses.push(u.iter().map(|&c| pos(c, v, n)).collect());
+            for i in 0..u.len() {
+                for j in (i + 1)..u.len() {
+                    clauses.push(vec![neg(u[i], v, n), neg(u[j], v, n)]);
+                }
+            }
+        }
+    }
+    // Givens fix their variable true.
+    for (cell, g) in givens.iter().enumerate() {
+        if let Some(v) = *g {
+            clauses.push(vec![pos(cell, v, n)]);
+        }
+    }
+    clauses
+}
+
+fn dpll(clauses: &[Clause], assign: &mut Vec<Option<bool>>) -> bool {
+    // Unit propagation to a fixpoint.
+    loop {
+        let mut changed = false;
+        for clause in clauses {
+            let mut unassigned: Option<Lit> = None;
+            let mut count = 0;
+            let mut satisfied = false;
+            for &lit in clause {
+                let v = lit.abs() as usize - 1;
+                let want = lit > 0;
+                match assign[v] {
+                    Some(b) if b == want => {
+                        satisfied = true;
+                        break;
+                    }
+                    Some(_) => {}
+                    None => {
+                        unassigned = Some(lit);
+                        count += 1;
+                    }
+                }
+            }
+            if satisfied {
+                continue;
+            }
+            if count == 0 {
+                return false; // every literal falsified -> conflict
+            }
+            if count == 1 {
+                let lit = unassigned.unwrap();
+                assign[lit.abs() as usize - 1] = Some(lit > 0);
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+    // Branch on the first unassigned variable.
+    match assign.iter().position(|a| a.is_none()) {
+        None => true,
+        Some(v) => {
+            for b in &[true, false] {
+                let mut next = assign.clone();
+                next[v] = Some(*b);
+                if dpll(clauses, &mut next) {
+                    *assign = next;
+                    return true;
+                }
+            }
+            false
+        }
+    }
+}
+
+// Solve a puzzle via the SAT backend, decoding the model back into a `Board`.
+fn solve_sat(s: &str, cfg: Config) -> Option<Board> {
+    let pr = make_units(cfg);
+    let n = cfg.n();
+    let mut givens = vec![None; cfg.cells()];
+    let mut current = -1;
+    for c in s.chars() {
+        if c == '\n' {
+            continue;
+        }
+        current += 1;
+        if c == '0' || c == '.' {
+            continue;
+        }
+        if let Some(d) = char_value(c) {
+            givens[current as usize] = Some(d);
+        }
+    }
+    let clauses = encode_cnf(&pr, &givens);
+    let mut assign = vec![None; cfg.cells() * n];
+    if !dpll(&clauses, &mut assign) {
+        return None;
+    }
+    let mut b = Board::new(cfg);
+    for cell in 0..cfg.cells() {
+        for v in 1..(n + 1) {
+            if assign[var(cell, v, n)] == Some(true) {
+                b.constraints[cell] = 1 << (v - 1);
+            }
+        }
+    }
+    Some(b)
+}
+
+fn solve_puzzle(s: &str, cfg: Config) -> Option<Board> {
+    let pr = make_units(cfg);
+    let mut b = Board::new(cfg);
     let mut current = -1;
     if PRINT {
         println!("puzzle: {}", s);
@@ -240,24 +586,122 @@ fn solve_puzzle(s: &str) -> Option<Board> {
         if c == '0' || c == '.' {
             continue;
         }
-        if let Some(d) = c.to_digit(10) {
-            if !b.assign(&pr, current as usize, d as usize) {
+        if let Some(d) = char_value(c) {
+            if !b.assign(&pr, current as usize, d) {
+                return None;
+            }
+        }
+    }
+    let mut nodes = 0;
+    b.search(&pr, &mut nodes)
+}
+
+// Builds a board from a puzzle string by assigning its givens, returning `None`
+// if a given is immediately contradictory.
+fn setup(s: &str, pr: &Problem) -> Option<Board> {
+    let mut b = Board::new(pr.cfg);
+    let mut current = -1;
+    for c in s.chars() {
+        if c == '\n' {
+            continue;
+        }
+        current += 1;
+        if c == '0' || c == '.' {
+            continue;
+        }
+        if let Some(d) = char_value(c) {
+            if !b.assign(pr, current as usize, d) {
                 return None;
             }
         }
     }
-    b.search(&pr)
+    Some(b)
+}
+
+// Solves each puzzle in `file`, reporting per-puzzle elapsed time and search
+// nodes plus aggregate statistics, in the style of the Rust benchmark suite's
+// sudoku solver.
+fn benchmark(file: &str, cfg: Config) {
+    let pr = make_units(cfg);
+    let strs = load(file).expect("couldn't load file");
+    let mut total = Duration::new(0, 0);
+    let mut max = Duration::new(0, 0);
+    let mut total_nodes = 0usize;
+    let mut solved = 0usize;
+    println!("{:>4}  {:>10}  {:>8}", "#", "time(ms)", "nodes");
+    for (i, line) in strs.iter().enumerate() {
+        if line.len() < cfg.cells() {
+            continue;
+        }
+        let mut b = match setup(line, &pr) {
+            Some(b) => b,
+            None => continue,
+        };
+        let mut nodes = 0;
+        let start = Instant::now();
+        let res = b.search(&pr, &mut nodes);
+        let elapsed = start.elapsed();
+        if res.is_some() {
+            solved += 1;
+        }
+        total += elapsed;
+        total_nodes += nodes;
+        if elapsed > max {
+            max = elapsed;
+        }
+        println!("{:>4}  {:>10.3}  {:>8}", i, millis(elapsed), nodes);
+    }
+    let secs = millis(total) / 1000.0;
+    println!("---");
+    println!("solved:    {}", solved);
+    println!("total:     {:.3} ms", millis(total));
+    if solved > 0 {
+        println!("mean:      {:.3} ms", millis(total) / solved as f64);
+    }
+    println!("max:       {:.3} ms", millis(max));
+    if secs > 0.0 {
+        println!("puzzles/s: {:.1}", solved as f64 / secs);
+    }
+    println!("nodes:     {}", total_nodes);
+}
+
+// Milliseconds as a float.
+fn millis(d: Duration) -> f64 {
+    d.as_secs() as f64 * 1000.0 + d.subsec_nanos() as f64 / 1_000_000.0
 }
 
 fn main() {
     // http://norvig.com/top95.txt
     let file = "top95.txt";
+    let cfg = Config { box_rows: 3, box_cols: 3 };
+    // Engine selection: "prop" (default), "sat", or "both" to cross-check.
+    let engine = env::args().nth(1).unwrap_or_else(|| "prop".to_string());
+    if engine == "gen" {
+        let pr = make_units(cfg);
+        println!("{}", generate(&pr, 17));
+        return;
+    }
+    if engine == "bench" {
+        benchmark(file, cfg);
+        return;
+    }
     let strs = load(file).expect("couldn't load file");
     for (i, line) in strs.iter().enumerate() {
-        if line.len() < 81 {
+        if line.len() < cfg.cells() {
             continue;
         }
-        let b = solve_puzzle(line).expect(&format!("failed to solve: {}", i));
+        let b = match engine.as_ref() {
+            "sat" => solve_sat(line, cfg).expect(&format!("failed to solve: {}", i)),
+            "both" => {
+                let prop = solve_puzzle(line, cfg).expect(&format!("failed to solve: {}", i));
+                let sat = solve_sat(line, cfg).expect(&format!("sat failed to solve: {}", i));
+                if prop != sat {
+                    panic!("engines disagree on puzzle {}", i);
+                }
+                prop
+            }
+            _ => solve_puzzle(line, cfg).expect(&format!("failed to solve: {}", i)),
+        };
         if PRINT {
             match b.solved() {
                 Outcome::Done => println!("solved ({})", i),
@@ -265,8 +709,9 @@ fn main() {
                 Outcome::Next(_) => println!("not done?? ({})", i),
             }
             let pb = render_board(&b);
-            for r in 0..9 {
-                let row = &pb[r * 9..r * 9 + 9];
+            let n = cfg.n();
+            for r in 0..n {
+                let row = &pb[r * n..r * n + n];
                 println!("{:?}", row);
             }
         }